@@ -0,0 +1,1026 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+//! # Direct Delegation
+//!
+//! A pallet that lets people stake their funds to:
+//! - Become a collator;
+//! - Delegate to a collator candidate;
+//! - Be appointed as an invulnerable, bond-free collator by governance.
+//!
+//! ## Overview
+//!
+//! The final collator set for a round is aggregated from two lists: the governance-appointed
+//! [`Invulnerables`], which are always present and require no bond, and the top
+//! `MaxSelectedCandidates` staked [`Candidates`], which compete for their seats on the open
+//! staking market. This lets a parachain guarantee liveness from trusted bootstrap collators
+//! while still allowing the staked set to fill (and eventually replace) the remaining slots.
+//!
+//! ### Terminology
+//!
+//! - **Candidate:** A user which locks up tokens to be included into the set of
+//!   authorities which author blocks and receive rewards for doing so.
+//!
+//! - **Invulnerable:** A collator appointed directly by governance. Invulnerables do not bond,
+//!   are always part of the author set, and cannot be forced out by `init_leave_candidates`.
+//!
+//! - **Collator:** A candidate (staked or invulnerable) that was chosen to collate this round.
+//!
+//! - **Round (= Session):** A fixed number of blocks in which the set of collators does not
+//!   change.
+//!
+//! ## Instancing
+//!
+//! This pallet is instantiable: a runtime may deploy it more than once (e.g. one instance per
+//! bonded asset, or per governance body) by binding a distinct `I` to each deployment. Every
+//! storage item, lock, and reward pool is scoped to its instance, so two instances over the same
+//! `Currency` never overlay locks on the same funds.
+
+pub use pallet::*;
+
+pub mod conviction;
+pub mod types;
+
+#[cfg(test)]
+mod mock;
+
+#[cfg(test)]
+mod tests;
+
+#[frame_support::pallet]
+pub mod pallet {
+	use crate::{
+		conviction::Conviction,
+		types::{
+			Bond, CollatorCandidate, CollatorStatus, Delegation, DelegationRequest, Delegator,
+			RewardPool, RoundIndex,
+		},
+	};
+	use frame_support::{
+		pallet_prelude::*,
+		traits::{Currency, Get, LockIdentifier, LockableCurrency, PalletInfo, WithdrawReasons},
+		BoundedBTreeMap,
+	};
+	use frame_system::pallet_prelude::*;
+	use sp_runtime::{
+		traits::{SaturatedConversion, Zero},
+		FixedPointNumber, FixedPointOperand,
+	};
+	use sp_staking::SessionIndex;
+	use sp_std::{collections::btree_set::BTreeSet, prelude::*};
+
+	const DELEGATOR_LOCK_PREFIX: LockIdentifier = *b"kbdelega";
+	const CANDIDATE_LOCK_PREFIX: LockIdentifier = *b"kbcandid";
+
+	#[pallet::config(with_default)]
+	pub trait Config<I: 'static = ()>: frame_system::Config {
+		/// Because this pallet emits events, it depends on the runtime's definition of an event.
+		#[pallet::no_default_bounds]
+		type Event: From<Event<Self, I>> + IsType<<Self as frame_system::Config>::Event>;
+
+		/// The currency staked by collator candidates and delegators. Each instance locks
+		/// balance independently, so two instances over the same `Currency` never overlay
+		/// locks on the same funds.
+		#[pallet::no_default]
+		type Currency: LockableCurrency<Self::AccountId, Moment = Self::BlockNumber>;
+
+		/// Maximum number of invulnerables that can be appointed by governance at once.
+		#[pallet::no_default]
+		#[pallet::constant]
+		type MaxInvulnerables: Get<u32>;
+
+		/// Maximum number of candidates the staking market can track at once.
+		#[pallet::no_default]
+		#[pallet::constant]
+		type MaxCandidates: Get<u32>;
+
+		/// Maximum number of distinct candidates a single delegator may back at once.
+		#[pallet::no_default]
+		#[pallet::constant]
+		type MaxDelegationsPerDelegator: Get<u32>;
+
+		/// Number of rounds, at `Conviction::None`, that delegated capital remains locked after a
+		/// revoke. Scaled by `conviction.lock_periods()` for stronger convictions.
+		#[pallet::no_default]
+		#[pallet::constant]
+		type StakeDuration: Get<RoundIndex>;
+
+		/// Maximum number of distinct unlock chunks a delegator may have pending at once.
+		#[pallet::no_default]
+		#[pallet::constant]
+		type MaxUnlockChunks: Get<u32>;
+
+		/// Number of rounds a scheduled revoke must wait before it becomes executable.
+		#[pallet::no_default]
+		#[pallet::constant]
+		type RevokeDelegationDelay: Get<RoundIndex>;
+
+		/// Maximum number of a candidate's delegations that are kept in `top_delegations` and
+		/// counted toward `total_counted`. Bounds a candidate's liability regardless of how many
+		/// delegations it attracts in total.
+		#[pallet::no_default]
+		#[pallet::constant]
+		type MaxDelegatorsPerCandidate: Get<u32>;
+	}
+
+	pub(crate) type BalanceOf<T, I = ()> =
+		<<T as Config<I>>::Currency as Currency<<T as frame_system::Config>::AccountId>>::Balance;
+
+	#[pallet::pallet]
+	#[pallet::generate_store(pub(super) trait Store)]
+	pub struct Pallet<T, I = ()>(_);
+
+	/// The collator candidates appointed by governance. These never bond, are always part of the
+	/// author set, and cannot be forced out via `init_leave_candidates`.
+	#[pallet::storage]
+	#[pallet::getter(fn invulnerables)]
+	pub type Invulnerables<T: Config<I>, I: 'static = ()> =
+		StorageValue<_, BoundedVec<T::AccountId, T::MaxInvulnerables>, ValueQuery>;
+
+	/// The staked collator candidates, keyed by their account.
+	#[pallet::storage]
+	#[pallet::getter(fn candidate_info)]
+	pub type Candidates<T: Config<I>, I: 'static = ()> = StorageMap<
+		_,
+		Blake2_128Concat,
+		T::AccountId,
+		CollatorCandidate<T::AccountId, BalanceOf<T, I>, T::MaxDelegatorsPerCandidate>,
+		OptionQuery,
+	>;
+
+	/// All candidates with their `total_counted` (zero included), in descending order so the top
+	/// `MaxSelectedCandidates` can be read off the front in O(1) and a candidate's position can
+	/// be found with a binary search in O(log n).
+	#[pallet::storage]
+	#[pallet::getter(fn candidate_pool)]
+	pub type CandidatePool<T: Config<I>, I: 'static = ()> =
+		StorageValue<_, BoundedVec<Bond<T::AccountId, BalanceOf<T, I>>, T::MaxCandidates>, ValueQuery>;
+
+	/// Maximum number of staked candidates that may be selected into the active set alongside
+	/// the invulnerables.
+	#[pallet::storage]
+	#[pallet::getter(fn max_selected_candidates)]
+	pub type MaxSelectedCandidates<T: Config<I>, I: 'static = ()> = StorageValue<_, u32, ValueQuery>;
+
+	/// Number of rounds after calling `init_leave_candidates` at which a candidate's `Leaving`
+	/// status is dated.
+	#[pallet::storage]
+	#[pallet::getter(fn exit_queue_delay)]
+	pub type ExitQueueDelay<T: Config<I>, I: 'static = ()> = StorageValue<_, RoundIndex, ValueQuery>;
+
+	/// The staked candidates selected for the current round, not including invulnerables. The
+	/// effective author set for the round is this set unioned with [`Invulnerables`].
+	#[pallet::storage]
+	#[pallet::getter(fn selected_candidates)]
+	pub type SelectedCandidates<T: Config<I>, I: 'static = ()> =
+		StorageValue<_, BoundedVec<T::AccountId, T::MaxCandidates>, ValueQuery>;
+
+	/// Every delegator's full set of delegations, keyed by the delegator's account.
+	#[pallet::storage]
+	#[pallet::getter(fn delegator_state)]
+	pub type DelegatorState<T: Config<I>, I: 'static = ()> = StorageMap<
+		_,
+		Blake2_128Concat,
+		T::AccountId,
+		Delegator<T::AccountId, BalanceOf<T, I>>,
+		OptionQuery,
+	>;
+
+	/// Capital that has left a delegation but is still conviction-locked, keyed by the round at
+	/// which it unlocks.
+	#[pallet::storage]
+	#[pallet::getter(fn unstaking)]
+	pub type Unstaking<T: Config<I>, I: 'static = ()> = StorageMap<
+		_,
+		Blake2_128Concat,
+		T::AccountId,
+		BoundedBTreeMap<RoundIndex, BalanceOf<T, I>, T::MaxUnlockChunks>,
+		ValueQuery,
+	>;
+
+	/// Pending revoke requests per delegator, keyed by the backed collator. Until a request is
+	/// executed, the delegation it targets still counts toward the collator's `total_backing`
+	/// and, while it is in the top-N, `total_counted`.
+	#[pallet::storage]
+	#[pallet::getter(fn delegation_scheduled_requests)]
+	pub type DelegationScheduledRequests<T: Config<I>, I: 'static = ()> = StorageMap<
+		_,
+		Blake2_128Concat,
+		T::AccountId,
+		BoundedBTreeMap<T::AccountId, DelegationRequest, T::MaxDelegationsPerDelegator>,
+		ValueQuery,
+	>;
+
+	/// Each collator's reward-per-token accumulator and running payout totals.
+	#[pallet::storage]
+	#[pallet::getter(fn reward_pools)]
+	pub type RewardPools<T: Config<I>, I: 'static = ()> =
+		StorageMap<_, Blake2_128Concat, T::AccountId, RewardPool<BalanceOf<T, I>>, ValueQuery>;
+
+	/// Rewards settled on a delegation that was removed before being claimed. Folded into the
+	/// next `claim_delegator_rewards` call since the originating delegation no longer exists.
+	#[pallet::storage]
+	#[pallet::getter(fn delegator_banked_rewards)]
+	pub type DelegatorBankedRewards<T: Config<I>, I: 'static = ()> =
+		StorageMap<_, Blake2_128Concat, T::AccountId, BalanceOf<T, I>, ValueQuery>;
+
+	/// The current round index. Advanced by one each time [`pallet_session::SessionManager::
+	/// start_session`] fires, i.e. once per session/round transition.
+	#[pallet::storage]
+	#[pallet::getter(fn current_round)]
+	pub type Round<T: Config<I>, I: 'static = ()> = StorageValue<_, RoundIndex, ValueQuery>;
+
+	#[pallet::genesis_config]
+	pub struct GenesisConfig<T: Config<I>, I: 'static = ()> {
+		pub invulnerables: Vec<T::AccountId>,
+		pub max_selected_candidates: u32,
+		pub exit_queue_delay: RoundIndex,
+	}
+
+	#[cfg(feature = "std")]
+	impl<T: Config<I>, I: 'static> Default for GenesisConfig<T, I> {
+		fn default() -> Self {
+			GenesisConfig {
+				invulnerables: Vec::new(),
+				max_selected_candidates: 0,
+				exit_queue_delay: 2,
+			}
+		}
+	}
+
+	#[pallet::genesis_build]
+	impl<T: Config<I>, I: 'static> GenesisBuild<T, I> for GenesisConfig<T, I> {
+		fn build(&self) {
+			let bounded_invulnerables: BoundedVec<T::AccountId, T::MaxInvulnerables> = self
+				.invulnerables
+				.clone()
+				.try_into()
+				.expect("genesis invulnerables exceed MaxInvulnerables");
+			<Invulnerables<T, I>>::put(bounded_invulnerables);
+			<MaxSelectedCandidates<T, I>>::put(self.max_selected_candidates);
+			<ExitQueueDelay<T, I>>::put(self.exit_queue_delay);
+		}
+	}
+
+	#[pallet::event]
+	#[pallet::generate_deposit(pub(super) fn deposit_event)]
+	pub enum Event<T: Config<I>, I: 'static = ()> {
+		/// Governance set a new invulnerable collator set. [invulnerables]
+		NewInvulnerables(Vec<T::AccountId>),
+		/// A new staked collator candidate joined the market. [candidate, bond]
+		JoinedCandidates(T::AccountId, BalanceOf<T, I>),
+		/// A candidate requested to leave the set of collator candidates. [candidate, round]
+		CandidateScheduledExit(T::AccountId, RoundIndex),
+		/// A delegator backed a collator candidate for the first time.
+		/// [delegator, candidate, conviction, locked, boosted_stake]
+		Delegation(T::AccountId, T::AccountId, Conviction, BalanceOf<T, I>, BalanceOf<T, I>),
+		/// A delegator increased an existing delegation.
+		/// [delegator, candidate, conviction, locked, boosted_stake]
+		DelegationIncreased(T::AccountId, T::AccountId, Conviction, BalanceOf<T, I>, BalanceOf<T, I>),
+		/// A delegator scheduled a revoke, executable from the given round. [delegator, candidate, when]
+		DelegationRevocationScheduled(T::AccountId, T::AccountId, RoundIndex),
+		/// A scheduled revoke was cancelled before execution. [delegator, candidate]
+		DelegationRevocationCancelled(T::AccountId, T::AccountId),
+		/// A scheduled revoke was executed; its capital is now unstaking. [delegator, candidate, round]
+		DelegationRevoked(T::AccountId, T::AccountId, RoundIndex),
+		/// A delegator unlocked previously unstaked capital. [delegator, amount]
+		DelegatorUnlocked(T::AccountId, BalanceOf<T, I>),
+		/// A delegation was bumped out of a candidate's top `MaxDelegatorsPerCandidate` by a
+		/// larger one and auto-scheduled for refund. [delegator, candidate, when]
+		DelegationBumped(T::AccountId, T::AccountId, RoundIndex),
+		/// A delegator claimed settled rewards across all of their delegations. [delegator, amount]
+		DelegatorRewardsClaimed(T::AccountId, BalanceOf<T, I>),
+	}
+
+	#[pallet::error]
+	pub enum Error<T, I = ()> {
+		/// The provided invulnerable list would exceed `MaxInvulnerables`.
+		TooManyInvulnerables,
+		/// The account is already a staked collator candidate.
+		CandidateExists,
+		/// The staking market already tracks `MaxCandidates` candidates.
+		TooManyCandidates,
+		/// The candidate is not part of the staking market.
+		CandidateDNE,
+		/// The candidate has already requested to leave.
+		AlreadyLeaving,
+		/// Invulnerables cannot join the staked candidates or go through their exit queue; they
+		/// are removed by `set_invulnerables` instead.
+		CannotBeInvulnerable,
+		/// The caller is already delegating; use `delegate_another_candidate` instead.
+		AlreadyDelegating,
+		/// The caller is not yet a delegator; use `join_delegators` instead.
+		DelegatorDNE,
+		/// The caller already delegates to this candidate.
+		AlreadyDelegatedCandidate,
+		/// The caller does not delegate to this candidate.
+		DelegationDNE,
+		/// The delegator already backs `MaxDelegationsPerDelegator` candidates, or already has
+		/// `MaxUnlockChunks` unlock chunks pending.
+		TooManyDelegations,
+		/// There is no unstaked capital ready to be unlocked yet.
+		NothingToUnlock,
+		/// There is already a pending scheduled request against this delegation.
+		PendingDelegationRequestAlreadyExists,
+		/// There is no pending scheduled request against this delegation.
+		PendingDelegationRequestDNE,
+		/// The scheduled request's execution round has not yet been reached.
+		PendingDelegationRequestNotDueYet,
+		/// The candidate's top `MaxDelegatorsPerCandidate` delegations are full and this
+		/// delegation's boosted stake is not large enough to displace the smallest of them.
+		InsufficientToDisplaceLowestDelegation,
+		/// There are no settled rewards available to claim.
+		NothingToClaim,
+	}
+
+	#[pallet::hooks]
+	impl<T: Config<I>, I: 'static> Hooks<BlockNumberFor<T>> for Pallet<T, I> {}
+
+	#[pallet::call]
+	impl<T: Config<I>, I: 'static> Pallet<T, I> {
+		/// Set the governance-appointed invulnerable collator set, replacing the previous one.
+		/// Invulnerables are always part of the author set, require no bond, and cannot be
+		/// forced out through the staked candidate exit queue.
+		#[pallet::weight(10_000 + T::DbWeight::get().writes(1))]
+		pub fn set_invulnerables(
+			origin: OriginFor<T>,
+			new: Vec<T::AccountId>,
+		) -> DispatchResultWithPostInfo {
+			ensure_root(origin)?;
+			let bounded: BoundedVec<T::AccountId, T::MaxInvulnerables> =
+				new.clone().try_into().map_err(|_| Error::<T, I>::TooManyInvulnerables)?;
+			<Invulnerables<T, I>>::put(bounded);
+			Self::deposit_event(Event::NewInvulnerables(new));
+			Ok(().into())
+		}
+
+		/// Join the set of staked collator candidates by bonding `bond`. Candidates compete on
+		/// `total_counted` (their bond plus their top delegations' boosted stake) for a seat
+		/// alongside the governance-appointed invulnerables.
+		#[pallet::weight(10_000 + T::DbWeight::get().reads_writes(1, 2))]
+		pub fn join_candidates(origin: OriginFor<T>, bond: BalanceOf<T, I>) -> DispatchResultWithPostInfo {
+			let who = ensure_signed(origin)?;
+			ensure!(!Self::is_invulnerable(&who), Error::<T, I>::CannotBeInvulnerable);
+			ensure!(!<Candidates<T, I>>::contains_key(&who), Error::<T, I>::CandidateExists);
+			ensure!(
+				(<CandidatePool<T, I>>::decode_len().unwrap_or(0) as u32) < T::MaxCandidates::get(),
+				Error::<T, I>::TooManyCandidates
+			);
+			let candidate = CollatorCandidate::new(who.clone(), bond);
+			T::Currency::set_lock(Self::candidate_lock_id(), &who, bond, WithdrawReasons::all());
+			Self::update_candidate_pool(&who, candidate.total_counted);
+			<Candidates<T, I>>::insert(&who, candidate);
+			Self::deposit_event(Event::JoinedCandidates(who, bond));
+			Ok(().into())
+		}
+
+		/// Request to leave the set of staked collator candidates: marks the candidate as
+		/// `Leaving` from round `now + ExitQueueDelay`. No call of this pallet executes the exit:
+		/// the candidate stays in [`CandidatePool`] and its bond stays locked. Invulnerables are
+		/// not part of this queue: they are removed by a subsequent `set_invulnerables` call instead.
+		#[pallet::weight(10_000 + T::DbWeight::get().reads_writes(1, 1))]
+		pub fn init_leave_candidates(origin: OriginFor<T>) -> DispatchResultWithPostInfo {
+			let who = ensure_signed(origin)?;
+			ensure!(!Self::is_invulnerable(&who), Error::<T, I>::CannotBeInvulnerable);
+			let mut candidate = <Candidates<T, I>>::get(&who).ok_or(Error::<T, I>::CandidateDNE)?;
+			ensure!(candidate.is_active(), Error::<T, I>::AlreadyLeaving);
+			let when = Self::round_index().saturating_add(<ExitQueueDelay<T, I>>::get());
+			candidate.status = CollatorStatus::Leaving(when);
+			<Candidates<T, I>>::insert(&who, candidate);
+			Self::deposit_event(Event::CandidateScheduledExit(who, when));
+			Ok(().into())
+		}
+
+		/// Join the set of delegators by delegating `amount` to `collator` with the given
+		/// `conviction`. Only `amount` (the `capital`) is locked; the boosted `stake` computed
+		/// from the conviction is what counts toward the collator's `total_backing` and, within
+		/// the top-N, `total_counted`.
+		#[pallet::weight(10_000 + T::DbWeight::get().reads_writes(2, 2))]
+		pub fn join_delegators(
+			origin: OriginFor<T>,
+			collator: T::AccountId,
+			amount: BalanceOf<T, I>,
+			conviction: Conviction,
+		) -> DispatchResultWithPostInfo
+		where
+			BalanceOf<T, I>: FixedPointOperand,
+		{
+			let who = ensure_signed(origin)?;
+			ensure!(<DelegatorState<T, I>>::get(&who).is_none(), Error::<T, I>::AlreadyDelegating);
+			let follows = conviction.stake(amount);
+			Self::add_delegation(&who, collator.clone(), conviction, follows)?;
+			Self::deposit_event(Event::Delegation(who, collator, conviction, amount, follows.stake));
+			Ok(().into())
+		}
+
+		/// Delegate to an additional collator candidate, up to `MaxDelegationsPerDelegator`.
+		#[pallet::weight(10_000 + T::DbWeight::get().reads_writes(2, 2))]
+		pub fn delegate_another_candidate(
+			origin: OriginFor<T>,
+			collator: T::AccountId,
+			amount: BalanceOf<T, I>,
+			conviction: Conviction,
+		) -> DispatchResultWithPostInfo
+		where
+			BalanceOf<T, I>: FixedPointOperand,
+		{
+			let who = ensure_signed(origin)?;
+			ensure!(<DelegatorState<T, I>>::contains_key(&who), Error::<T, I>::DelegatorDNE);
+			let follows = conviction.stake(amount);
+			Self::add_delegation(&who, collator.clone(), conviction, follows)?;
+			Self::deposit_event(Event::Delegation(who, collator, conviction, amount, follows.stake));
+			Ok(().into())
+		}
+
+		/// Increase an existing delegation to `collator` by `more`, optionally re-costing it under
+		/// a new `conviction`. The full new capital is re-locked, the candidate's `total_backing`
+		/// is adjusted by the difference in boosted `stake`, and the delegation is re-ranked in
+		/// `top_delegations`.
+		#[pallet::weight(10_000 + T::DbWeight::get().reads_writes(2, 2))]
+		pub fn delegator_stake_more(
+			origin: OriginFor<T>,
+			collator: T::AccountId,
+			more: BalanceOf<T, I>,
+			conviction: Conviction,
+		) -> DispatchResultWithPostInfo
+		where
+			BalanceOf<T, I>: FixedPointOperand,
+		{
+			let who = ensure_signed(origin)?;
+			let mut delegator = <DelegatorState<T, I>>::get(&who).ok_or(Error::<T, I>::DelegatorDNE)?;
+			let mut candidate = <Candidates<T, I>>::get(&collator).ok_or(Error::<T, I>::CandidateDNE)?;
+			let delegation = delegator
+				.delegations
+				.iter_mut()
+				.find(|d| d.owner == collator)
+				.ok_or(Error::<T, I>::DelegationDNE)?;
+
+			let pool = <RewardPools<T, I>>::get(&collator);
+			let is_counted = candidate.top_delegations.iter().any(|bond| bond.owner == who);
+			Self::settle_delegation(delegation, &pool, is_counted);
+
+			let new_capital = delegation.follows.capital.saturating_add(more);
+			let new_follows = conviction.stake(new_capital);
+			let old_stake = delegation.follows.stake;
+			candidate.total_backing =
+				candidate.total_backing.saturating_sub(old_stake).saturating_add(new_follows.stake);
+
+			// Re-run the top-N displacement path rather than only patching the entry in place: a
+			// delegation that wasn't counted before may now be large enough to bump its way in, and
+			// one that already was must free its old slot before re-inserting so it can't evict
+			// itself.
+			if let Some(position) =
+				candidate.top_delegations.iter().position(|bond| bond.owner == who)
+			{
+				candidate.top_delegations.remove(position);
+				candidate.total_counted = candidate.total_counted.saturating_sub(old_stake);
+			}
+			let top_full =
+				(candidate.top_delegations.len() as u32) >= T::MaxDelegatorsPerCandidate::get();
+			let displaces_lowest = candidate
+				.lowest_top_delegation()
+				.map_or(true, |lowest| new_follows.stake > lowest.amount);
+			let evicted = if !top_full || displaces_lowest {
+				let evicted = candidate
+					.insert_top_delegation(Bond { owner: who.clone(), amount: new_follows.stake });
+				candidate.total_counted = candidate.total_counted.saturating_add(new_follows.stake);
+				evicted
+			} else {
+				None
+			};
+			if let Some(evicted) = &evicted {
+				candidate.total_counted = candidate.total_counted.saturating_sub(evicted.amount);
+				Self::settle_evicted_delegation(&collator, evicted);
+			}
+
+			delegator.total = delegator.total.saturating_add(more);
+			delegation.conviction = conviction;
+			delegation.follows = new_follows;
+
+			Self::update_candidate_pool(&collator, candidate.total_counted);
+			<Candidates<T, I>>::insert(&collator, candidate);
+			<DelegatorState<T, I>>::insert(&who, delegator);
+			Self::sync_delegator_lock(&who);
+			if let Some(evicted) = evicted {
+				let when = Self::schedule_auto_refund(&evicted.owner, &collator)?;
+				Self::deposit_event(Event::DelegationBumped(evicted.owner, collator.clone(), when));
+			}
+			Self::deposit_event(Event::DelegationIncreased(
+				who,
+				collator,
+				conviction,
+				more,
+				new_follows.stake,
+			));
+			Ok(().into())
+		}
+
+		/// Schedule a revoke of the delegation to `collator`. The delegation keeps counting
+		/// toward the candidate's totals until `execute_delegation_request` is called on or
+		/// after the returned round.
+		#[pallet::weight(10_000 + T::DbWeight::get().reads_writes(1, 1))]
+		pub fn schedule_revoke_delegation(
+			origin: OriginFor<T>,
+			collator: T::AccountId,
+		) -> DispatchResultWithPostInfo {
+			let who = ensure_signed(origin)?;
+			let when = Self::schedule_one_revoke(&who, &collator)?;
+			Self::deposit_event(Event::DelegationRevocationScheduled(who, collator, when));
+			Ok(().into())
+		}
+
+		/// Schedule a revoke against every collator the caller currently delegates to, as a batch
+		/// of independently-executable scheduled requests.
+		#[pallet::weight(10_000 + T::DbWeight::get().reads_writes(2, 2))]
+		pub fn schedule_leave_delegators(origin: OriginFor<T>) -> DispatchResultWithPostInfo {
+			let who = ensure_signed(origin)?;
+			let delegator = <DelegatorState<T, I>>::get(&who).ok_or(Error::<T, I>::DelegatorDNE)?;
+			for delegation in delegator.delegations {
+				// A collator that already has a pending request from an earlier
+				// `schedule_revoke_delegation` call is left alone rather than aborting the whole
+				// batch: the caller's intent to eventually leave every delegation still holds, and
+				// the existing request will execute on its own schedule.
+				match Self::schedule_one_revoke(&who, &delegation.owner) {
+					Ok(when) => Self::deposit_event(Event::DelegationRevocationScheduled(
+						who.clone(),
+						delegation.owner,
+						when,
+					)),
+					Err(e) if e == Error::<T, I>::PendingDelegationRequestAlreadyExists.into() => {
+						continue
+					},
+					Err(e) => return Err(e.into()),
+				}
+			}
+			Ok(().into())
+		}
+
+		/// Cancel a pending scheduled revoke against `collator` before it executes.
+		#[pallet::weight(10_000 + T::DbWeight::get().reads_writes(1, 1))]
+		pub fn cancel_delegation_request(
+			origin: OriginFor<T>,
+			collator: T::AccountId,
+		) -> DispatchResultWithPostInfo {
+			let who = ensure_signed(origin)?;
+			<DelegationScheduledRequests<T, I>>::try_mutate(&who, |requests| -> DispatchResult {
+				requests.remove(&collator).ok_or(Error::<T, I>::PendingDelegationRequestDNE)?;
+				Ok(())
+			})?;
+			Self::deposit_event(Event::DelegationRevocationCancelled(who, collator));
+			Ok(().into())
+		}
+
+		/// Execute a delegator's matured scheduled revoke against `collator`. Permissionless:
+		/// anyone may call this once the request's round has been reached, removing the
+		/// delegation and starting its conviction-scaled unlock. The locked capital is not
+		/// returned immediately: it becomes claimable via `unlock_unstaked` only once
+		/// `conviction.lock_periods().max(1) * StakeDuration` rounds have passed.
+		#[pallet::weight(10_000 + T::DbWeight::get().reads_writes(2, 2))]
+		pub fn execute_delegation_request(
+			origin: OriginFor<T>,
+			delegator: T::AccountId,
+			collator: T::AccountId,
+		) -> DispatchResultWithPostInfo {
+			ensure_signed(origin)?;
+			let request = <DelegationScheduledRequests<T, I>>::try_mutate(
+				&delegator,
+				|requests| -> Result<DelegationRequest, DispatchError> {
+					let request =
+						*requests.get(&collator).ok_or(Error::<T, I>::PendingDelegationRequestDNE)?;
+					ensure!(
+						request.when <= Self::round_index(),
+						Error::<T, I>::PendingDelegationRequestNotDueYet
+					);
+					requests.remove(&collator);
+					Ok(request)
+				},
+			)?;
+			Self::remove_delegation(&delegator, &collator)?;
+			Self::deposit_event(Event::DelegationRevoked(delegator, collator, request.when));
+			Ok(().into())
+		}
+
+		/// Unlock any previously revoked capital whose conviction lock has matured.
+		#[pallet::weight(10_000 + T::DbWeight::get().reads_writes(1, 1))]
+		pub fn unlock_unstaked(origin: OriginFor<T>) -> DispatchResultWithPostInfo {
+			let who = ensure_signed(origin)?;
+			let now = Self::round_index();
+			let mut pending = <Unstaking<T, I>>::get(&who);
+			let matured: Vec<RoundIndex> =
+				pending.iter().filter(|(round, _)| **round <= now).map(|(round, _)| *round).collect();
+			ensure!(!matured.is_empty(), Error::<T, I>::NothingToUnlock);
+
+			let mut released: BalanceOf<T, I> = Zero::zero();
+			for round in matured {
+				if let Some(amount) = pending.remove(&round) {
+					released = released.saturating_add(amount);
+				}
+			}
+			<Unstaking<T, I>>::insert(&who, pending);
+			Self::sync_delegator_lock(&who);
+			Self::deposit_event(Event::DelegatorUnlocked(who, released));
+			Ok(().into())
+		}
+
+		/// Settle and claim rewards across every one of the caller's delegations, plus any
+		/// rewards banked from delegations that were removed before being claimed.
+		#[pallet::weight(10_000 + T::DbWeight::get().reads_writes(2, 2))]
+		pub fn claim_delegator_rewards(origin: OriginFor<T>) -> DispatchResultWithPostInfo
+		where
+			BalanceOf<T, I>: FixedPointOperand,
+		{
+			let who = ensure_signed(origin)?;
+			let mut delegator = <DelegatorState<T, I>>::get(&who).ok_or(Error::<T, I>::DelegatorDNE)?;
+			let mut claimed = <DelegatorBankedRewards<T, I>>::take(&who);
+
+			for delegation in delegator.delegations.iter_mut() {
+				let pool = <RewardPools<T, I>>::get(&delegation.owner);
+				let is_counted = <Candidates<T, I>>::get(&delegation.owner)
+					.map_or(false, |c| c.top_delegations.iter().any(|bond| bond.owner == who));
+				Self::settle_delegation(delegation, &pool, is_counted);
+				if !delegation.pending_rewards.is_zero() {
+					let amount = delegation.pending_rewards;
+					delegation.pending_rewards = Zero::zero();
+					claimed = claimed.saturating_add(amount);
+					<RewardPools<T, I>>::mutate(&delegation.owner, |pool| {
+						pool.total_rewards_claimed = pool.total_rewards_claimed.saturating_add(amount);
+					});
+				}
+			}
+			<DelegatorState<T, I>>::insert(&who, delegator);
+
+			ensure!(!claimed.is_zero(), Error::<T, I>::NothingToClaim);
+			Self::deposit_event(Event::DelegatorRewardsClaimed(who, claimed));
+			Ok(().into())
+		}
+	}
+
+	impl<T: Config<I>, I: 'static> Pallet<T, I> {
+		/// Add a new delegation of `follows` (already boosted by `conviction`) from `who` to
+		/// `collator`, locking `follows.capital` and folding `follows.stake` into the candidate's
+		/// `total_backing`.
+		///
+		/// If the candidate's top `MaxDelegatorsPerCandidate` delegations are already full, this
+		/// delegation is rejected unless it is large enough to displace the current smallest
+		/// entry; in that case the displaced delegation is auto-scheduled for refund.
+		fn add_delegation(
+			who: &T::AccountId,
+			collator: T::AccountId,
+			conviction: Conviction,
+			follows: crate::types::Follows<BalanceOf<T, I>>,
+		) -> DispatchResult
+		where
+			BalanceOf<T, I>: FixedPointOperand,
+		{
+			let mut candidate = <Candidates<T, I>>::get(&collator).ok_or(Error::<T, I>::CandidateDNE)?;
+			let mut delegator = <DelegatorState<T, I>>::get(who).unwrap_or_else(|| Delegator {
+				id: who.clone(),
+				delegations: Vec::new(),
+				total: Zero::zero(),
+			});
+			ensure!(
+				!delegator.delegations.iter().any(|d| d.owner == collator),
+				Error::<T, I>::AlreadyDelegatedCandidate
+			);
+			ensure!(
+				(delegator.delegations.len() as u32) < T::MaxDelegationsPerDelegator::get(),
+				Error::<T, I>::TooManyDelegations
+			);
+			if let Some(lowest) = candidate.lowest_top_delegation() {
+				let top_full =
+					(candidate.top_delegations.len() as u32) >= T::MaxDelegatorsPerCandidate::get();
+				ensure!(
+					!top_full || follows.stake > lowest.amount,
+					Error::<T, I>::InsufficientToDisplaceLowestDelegation
+				);
+			}
+
+			let reward_per_token_checkpoint =
+				<RewardPools<T, I>>::get(&collator).last_recorded_reward_per_token;
+			delegator.delegations.push(Delegation {
+				owner: collator.clone(),
+				conviction,
+				follows,
+				reward_per_token_checkpoint,
+				pending_rewards: Zero::zero(),
+			});
+			delegator.total = delegator.total.saturating_add(follows.capital);
+			candidate.total_backing = candidate.total_backing.saturating_add(follows.stake);
+
+			let evicted =
+				candidate.insert_top_delegation(Bond { owner: who.clone(), amount: follows.stake });
+			candidate.total_counted = candidate.total_counted.saturating_add(follows.stake);
+			if let Some(evicted) = &evicted {
+				candidate.total_counted = candidate.total_counted.saturating_sub(evicted.amount);
+				Self::settle_evicted_delegation(&collator, evicted);
+			}
+
+			Self::update_candidate_pool(&collator, candidate.total_counted);
+			<Candidates<T, I>>::insert(&collator, candidate);
+			<DelegatorState<T, I>>::insert(who, delegator);
+			Self::sync_delegator_lock(who);
+
+			if let Some(evicted) = evicted {
+				let when = Self::schedule_auto_refund(&evicted.owner, &collator)?;
+				Self::deposit_event(Event::DelegationBumped(evicted.owner, collator, when));
+			}
+			Ok(())
+		}
+
+		/// Sum of `who`'s capital across every unstaking chunk not yet released by
+		/// `unlock_unstaked`.
+		fn total_unstaking(who: &T::AccountId) -> BalanceOf<T, I> {
+			<Unstaking<T, I>>::get(who)
+				.values()
+				.fold(Zero::zero(), |acc: BalanceOf<T, I>, amount| acc.saturating_add(*amount))
+		}
+
+		/// Recompute and apply `who`'s currency lock as the sum of their active delegated capital
+		/// (from [`DelegatorState`]) and any capital still unstaking (from [`Unstaking`]), removing
+		/// the lock entirely once both are zero. Called after any change to either set, so revoked
+		/// capital stays locked until its conviction delay has actually matured.
+		fn sync_delegator_lock(who: &T::AccountId) {
+			let active = <DelegatorState<T, I>>::get(who).map(|d| d.total).unwrap_or_else(Zero::zero);
+			let total = active.saturating_add(Self::total_unstaking(who));
+			if total.is_zero() {
+				T::Currency::remove_lock(Self::delegator_lock_id(), who);
+			} else {
+				T::Currency::set_lock(Self::delegator_lock_id(), who, total, WithdrawReasons::all());
+			}
+		}
+
+		/// This instance's lock id for delegated capital, derived from [`DELEGATOR_LOCK_PREFIX`] by
+		/// folding in the pallet's index in `construct_runtime!`. Two instances over the same
+		/// `Currency` are therefore guaranteed distinct lock ids and never overlay locks on the
+		/// same funds.
+		fn delegator_lock_id() -> LockIdentifier {
+			Self::instance_lock_id(DELEGATOR_LOCK_PREFIX)
+		}
+
+		/// As [`Self::delegator_lock_id`], for the lock a candidate's own bond is held under.
+		fn candidate_lock_id() -> LockIdentifier {
+			Self::instance_lock_id(CANDIDATE_LOCK_PREFIX)
+		}
+
+		fn instance_lock_id(mut prefix: LockIdentifier) -> LockIdentifier {
+			let index = <T as frame_system::Config>::PalletInfo::index::<Self>().unwrap_or_default();
+			prefix[7] = index as u8;
+			prefix
+		}
+
+		/// Keep [`CandidatePool`] sorted in descending order of `total_counted`.
+		fn update_candidate_pool(collator: &T::AccountId, total_counted: BalanceOf<T, I>) {
+			<CandidatePool<T, I>>::mutate(|pool| {
+				if let Some(position) = pool.iter().position(|bond| &bond.owner == collator) {
+					pool.remove(position);
+				}
+				let bond = Bond { owner: collator.clone(), amount: total_counted };
+				let index = pool.partition_point(|probe| probe.amount >= bond.amount);
+				let _ = pool.try_insert(index, bond);
+			});
+		}
+
+		/// Fold a collator's newly-minted rewards into its [`RewardPool`] accumulator.
+		///
+		/// `pot_balance` is the reward pot's current free balance; together with the pool's
+		/// running claimed totals it gives the all-time total paid into the pool, so the delta
+		/// since `last_recorded_total_payouts` is exactly what was minted since the last call.
+		/// The accumulator is only ever moved forward: a `pot_balance` dip (e.g. a transient
+		/// deficit near the existential deposit) can shrink the computed delta to zero, but never
+		/// claws back `last_recorded_reward_per_token`.
+		pub fn record_reward_payout(collator: &T::AccountId, pot_balance: BalanceOf<T, I>)
+		where
+			BalanceOf<T, I>: FixedPointOperand,
+		{
+			let counted_stake =
+				<Candidates<T, I>>::get(collator).map(|c| c.total_counted).unwrap_or_else(Zero::zero);
+			<RewardPools<T, I>>::mutate(collator, |pool| {
+				let total_payouts = pot_balance
+					.saturating_add(pool.total_rewards_claimed)
+					.saturating_add(pool.total_commission_claimed);
+				if total_payouts <= pool.last_recorded_total_payouts || counted_stake.is_zero() {
+					pool.last_recorded_total_payouts =
+						pool.last_recorded_total_payouts.max(total_payouts);
+					return;
+				}
+				let new_rewards = total_payouts.saturating_sub(pool.last_recorded_total_payouts);
+				let delta = crate::types::RewardPerToken::saturating_from_rational(
+					new_rewards.saturated_into::<u128>(),
+					counted_stake.saturated_into::<u128>(),
+				);
+				pool.last_recorded_reward_per_token =
+					pool.last_recorded_reward_per_token.saturating_add(delta);
+				pool.last_recorded_total_payouts = total_payouts;
+			});
+		}
+
+		/// Settle `delegation`'s rewards against `pool`'s current accumulator, folding the
+		/// accrued amount into `pending_rewards` and advancing the delegation's checkpoint.
+		/// Must be called before any change to `delegation.follows.stake` so the change cannot
+		/// retroactively affect rewards already earned under the old stake.
+		///
+		/// `is_counted` must reflect whether the delegation is currently in its candidate's
+		/// `top_delegations`: `record_reward_payout` only spreads rewards over `total_counted`, so
+		/// a delegation sitting below the top-N cut-off must not accrue against it either, or
+		/// delegator claims could exceed what was actually minted into the pool.
+		fn settle_delegation(
+			delegation: &mut Delegation<T::AccountId, BalanceOf<T, I>>,
+			pool: &RewardPool<BalanceOf<T, I>>,
+			is_counted: bool,
+		) where
+			BalanceOf<T, I>: FixedPointOperand,
+		{
+			if is_counted {
+				let delta = pool
+					.last_recorded_reward_per_token
+					.saturating_sub(delegation.reward_per_token_checkpoint);
+				let accrued = delta.saturating_mul_int(delegation.follows.stake);
+				delegation.pending_rewards = delegation.pending_rewards.saturating_add(accrued);
+			}
+			delegation.reward_per_token_checkpoint = pool.last_recorded_reward_per_token;
+		}
+
+		/// Settle and bank the rewards a delegation earned while it was still counted, right as it
+		/// is bumped out of `collator`'s `top_delegations` by a larger one. Must run before the
+		/// eviction is otherwise acted on: once the delegation drops out of `top_delegations` its
+		/// `is_counted` reads `false` everywhere else, and any rewards accrued up to this point
+		/// would be silently dropped instead of settled.
+		fn settle_evicted_delegation(collator: &T::AccountId, evicted: &Bond<T::AccountId, BalanceOf<T, I>>)
+		where
+			BalanceOf<T, I>: FixedPointOperand,
+		{
+			let pool = <RewardPools<T, I>>::get(collator);
+			<DelegatorState<T, I>>::mutate(&evicted.owner, |maybe_delegator| {
+				let delegator = match maybe_delegator {
+					Some(delegator) => delegator,
+					None => return,
+				};
+				let delegation = match delegator.delegations.iter_mut().find(|d| &d.owner == collator) {
+					Some(delegation) => delegation,
+					None => return,
+				};
+				Self::settle_delegation(delegation, &pool, true);
+				if !delegation.pending_rewards.is_zero() {
+					let amount = delegation.pending_rewards;
+					delegation.pending_rewards = Zero::zero();
+					<DelegatorBankedRewards<T, I>>::mutate(&evicted.owner, |banked| {
+						*banked = banked.saturating_add(amount);
+					});
+				}
+			});
+		}
+
+		/// Auto-schedule an immediately-executable refund for a delegation that was just bumped
+		/// out of a candidate's top `MaxDelegatorsPerCandidate`, overriding any later
+		/// user-scheduled request for the same pair.
+		fn schedule_auto_refund(
+			delegator: &T::AccountId,
+			collator: &T::AccountId,
+		) -> Result<RoundIndex, DispatchError> {
+			let when = Self::round_index();
+			<DelegationScheduledRequests<T, I>>::try_mutate(delegator, |requests| -> DispatchResult {
+				requests
+					.try_insert(collator.clone(), DelegationRequest { when })
+					.map_err(|_| Error::<T, I>::TooManyDelegations)?;
+				Ok(())
+			})?;
+			Ok(when)
+		}
+
+		/// Record a pending revoke request for `who` against `collator`, due `RevokeDelegationDelay`
+		/// rounds from now. Does not touch the candidate's totals: the delegation still counts
+		/// until the request is executed.
+		fn schedule_one_revoke(
+			who: &T::AccountId,
+			collator: &T::AccountId,
+		) -> Result<RoundIndex, DispatchError> {
+			let delegator = <DelegatorState<T, I>>::get(who).ok_or(Error::<T, I>::DelegatorDNE)?;
+			ensure!(
+				delegator.delegations.iter().any(|d| &d.owner == collator),
+				Error::<T, I>::DelegationDNE
+			);
+			let when = Self::round_index().saturating_add(T::RevokeDelegationDelay::get());
+			<DelegationScheduledRequests<T, I>>::try_mutate(who, |requests| -> DispatchResult {
+				ensure!(
+					!requests.contains_key(collator),
+					Error::<T, I>::PendingDelegationRequestAlreadyExists
+				);
+				requests
+					.try_insert(collator.clone(), DelegationRequest { when })
+					.map_err(|_| Error::<T, I>::TooManyDelegations)?;
+				Ok(())
+			})?;
+			Ok(when)
+		}
+
+		/// Remove `who`'s delegation to `collator`, subtract its boosted stake from the
+		/// candidate's `total_backing` (and `total_counted` if it was in the top-N), and
+		/// schedule the locked capital to unlock after
+		/// `conviction.lock_periods().max(1) * StakeDuration` rounds.
+		fn remove_delegation(who: &T::AccountId, collator: &T::AccountId) -> DispatchResult
+		where
+			BalanceOf<T, I>: FixedPointOperand,
+		{
+			let mut delegator = <DelegatorState<T, I>>::get(who).ok_or(Error::<T, I>::DelegatorDNE)?;
+			let index = delegator
+				.delegations
+				.iter()
+				.position(|d| &d.owner == collator)
+				.ok_or(Error::<T, I>::DelegationDNE)?;
+			let mut delegation = delegator.delegations.remove(index);
+			delegator.total = delegator.total.saturating_sub(delegation.follows.capital);
+
+			let mut candidate = <Candidates<T, I>>::get(collator);
+			let is_counted = candidate
+				.as_ref()
+				.map_or(false, |c| c.top_delegations.iter().any(|bond| &bond.owner == who));
+			let pool = <RewardPools<T, I>>::get(collator);
+			Self::settle_delegation(&mut delegation, &pool, is_counted);
+			if !delegation.pending_rewards.is_zero() {
+				<DelegatorBankedRewards<T, I>>::mutate(who, |banked| {
+					*banked = banked.saturating_add(delegation.pending_rewards);
+				});
+			}
+
+			if let Some(mut candidate) = candidate {
+				candidate.total_backing = candidate.total_backing.saturating_sub(delegation.follows.stake);
+				if let Some(position) =
+					candidate.top_delegations.iter().position(|bond| &bond.owner == who)
+				{
+					candidate.top_delegations.remove(position);
+					candidate.total_counted =
+						candidate.total_counted.saturating_sub(delegation.follows.stake);
+				}
+				Self::update_candidate_pool(collator, candidate.total_counted);
+				<Candidates<T, I>>::insert(collator, candidate);
+			}
+
+			// `lock_periods().max(1)` so `Conviction::None` (whose `lock_periods()` is `0`) still
+			// serves the flat `StakeDuration` delay documented on that constant, rather than
+			// unlocking in the very same round it was revoked.
+			let unlock_round = Self::round_index().saturating_add(
+				delegation.conviction.lock_periods().max(1).saturating_mul(T::StakeDuration::get()),
+			);
+			<Unstaking<T, I>>::try_mutate(who, |pending| -> DispatchResult {
+				let existing = pending.get(&unlock_round).copied().unwrap_or_else(Zero::zero);
+				pending
+					.try_insert(unlock_round, existing.saturating_add(delegation.follows.capital))
+					.map_err(|_| Error::<T, I>::TooManyDelegations)?;
+				Ok(())
+			})?;
+
+			if delegator.delegations.is_empty() {
+				<DelegatorState<T, I>>::remove(who);
+			} else {
+				<DelegatorState<T, I>>::insert(who, delegator);
+			}
+			Self::sync_delegator_lock(who);
+			Ok(())
+		}
+		/// Whether `who` is part of the current governance-appointed invulnerable set.
+		pub fn is_invulnerable(who: &T::AccountId) -> bool {
+			<Invulnerables<T, I>>::get().contains(who)
+		}
+
+		/// The current round index, as of the last session/round transition.
+		fn round_index() -> RoundIndex {
+			<Round<T, I>>::get()
+		}
+
+		/// Recompute [`SelectedCandidates`] as the top `MaxSelectedCandidates` entries of
+		/// [`CandidatePool`], which is kept sorted by `total_counted`, and cache the result.
+		pub fn recompute_selected_candidates() -> BoundedVec<T::AccountId, T::MaxCandidates> {
+			let take = <MaxSelectedCandidates<T, I>>::get() as usize;
+			let selected: Vec<T::AccountId> =
+				<CandidatePool<T, I>>::get().iter().take(take).map(|bond| bond.owner.clone()).collect();
+			let bounded: BoundedVec<T::AccountId, T::MaxCandidates> =
+				selected.try_into().unwrap_or_default();
+			<SelectedCandidates<T, I>>::put(bounded.clone());
+			bounded
+		}
+
+		/// The author set for the *next* round: the selected staked candidates unioned with the
+		/// current invulnerables. Invulnerables are included unconditionally, independent of
+		/// whether they also happen to hold a (bond-free) candidate entry.
+		pub fn compute_author_set() -> Vec<T::AccountId> {
+			let invulnerables = <Invulnerables<T, I>>::get();
+			let selected = Self::recompute_selected_candidates();
+			let mut authors: BTreeSet<T::AccountId> = invulnerables.iter().cloned().collect();
+			authors.extend(selected.iter().cloned());
+			authors.into_iter().collect()
+		}
+	}
+
+	/// Union the selected staked candidates with the invulnerables when a new session's author
+	/// set is requested, so invulnerables always collate regardless of the staking market.
+	impl<T: Config<I>, I: 'static> pallet_session::SessionManager<T::AccountId> for Pallet<T, I> {
+		fn new_session(_new_index: SessionIndex) -> Option<Vec<T::AccountId>> {
+			Some(Self::compute_author_set())
+		}
+
+		fn start_session(_start_index: SessionIndex) {
+			<Round<T, I>>::mutate(|round| *round = round.saturating_add(1));
+		}
+
+		fn end_session(_end_index: SessionIndex) {}
+	}
+}
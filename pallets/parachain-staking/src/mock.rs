@@ -0,0 +1,117 @@
+//! Test runtime for the parachain-staking pallet.
+
+use crate as parachain_staking;
+use frame_support::{
+	construct_runtime, parameter_types,
+	traits::ConstU32,
+};
+use sp_core::H256;
+use sp_runtime::{
+	testing::Header,
+	traits::{BlakeTwo256, IdentityLookup},
+};
+
+type UncheckedExtrinsic = frame_system::mocking::MockUncheckedExtrinsic<Test>;
+type Block = frame_system::mocking::MockBlock<Test>;
+
+construct_runtime!(
+	pub enum Test where
+		Block = Block,
+		NodeBlock = Block,
+		UncheckedExtrinsic = UncheckedExtrinsic,
+	{
+		System: frame_system::{Pallet, Call, Config, Storage, Event<T>},
+		Balances: pallet_balances::{Pallet, Call, Storage, Config<T>, Event<T>},
+		ParachainStaking: parachain_staking::{Pallet, Call, Storage, Config<T>, Event<T>},
+	}
+);
+
+parameter_types! {
+	pub const BlockHashCount: u64 = 250;
+}
+
+impl frame_system::Config for Test {
+	type BaseCallFilter = frame_support::traits::Everything;
+	type BlockWeights = ();
+	type BlockLength = ();
+	type DbWeight = ();
+	type Origin = Origin;
+	type Call = Call;
+	type Index = u64;
+	type BlockNumber = u64;
+	type Hash = H256;
+	type Hashing = BlakeTwo256;
+	type AccountId = u64;
+	type Lookup = IdentityLookup<Self::AccountId>;
+	type Header = Header;
+	type Event = Event;
+	type BlockHashCount = BlockHashCount;
+	type Version = ();
+	type PalletInfo = PalletInfo;
+	type AccountData = pallet_balances::AccountData<u128>;
+	type OnNewAccount = ();
+	type OnKilledAccount = ();
+	type SystemWeightInfo = ();
+	type SS58Prefix = ();
+	type OnSetCode = ();
+	type MaxConsumers = ConstU32<16>;
+}
+
+parameter_types! {
+	pub const ExistentialDeposit: u128 = 1;
+}
+
+impl pallet_balances::Config for Test {
+	type MaxLocks = ConstU32<50>;
+	type MaxReserves = ();
+	type ReserveIdentifier = [u8; 8];
+	type Balance = u128;
+	type Event = Event;
+	type DustRemoval = ();
+	type ExistentialDeposit = ExistentialDeposit;
+	type AccountStore = System;
+	type WeightInfo = ();
+}
+
+parameter_types! {
+	pub const MaxInvulnerables: u32 = 4;
+	pub const MaxCandidates: u32 = 16;
+	pub const MaxDelegationsPerDelegator: u32 = 4;
+	pub const StakeDuration: u32 = 2;
+	pub const MaxUnlockChunks: u32 = 4;
+	pub const RevokeDelegationDelay: u32 = 2;
+	pub const MaxDelegatorsPerCandidate: u32 = 2;
+}
+
+impl parachain_staking::Config for Test {
+	type Event = Event;
+	type Currency = Balances;
+	type MaxInvulnerables = MaxInvulnerables;
+	type MaxCandidates = MaxCandidates;
+	type MaxDelegationsPerDelegator = MaxDelegationsPerDelegator;
+	type StakeDuration = StakeDuration;
+	type MaxUnlockChunks = MaxUnlockChunks;
+	type RevokeDelegationDelay = RevokeDelegationDelay;
+	type MaxDelegatorsPerCandidate = MaxDelegatorsPerCandidate;
+}
+
+/// Build genesis storage according to the mock runtime's defaults, with `balances` pre-funded.
+pub fn new_test_ext(balances: Vec<(u64, u128)>) -> sp_io::TestExternalities {
+	let mut storage = frame_system::GenesisConfig::default().build_storage::<Test>().unwrap();
+	pallet_balances::GenesisConfig::<Test> { balances }.assimilate_storage(&mut storage).unwrap();
+	parachain_staking::GenesisConfig::<Test> {
+		invulnerables: vec![],
+		max_selected_candidates: 4,
+		exit_queue_delay: 2,
+	}
+	.assimilate_storage(&mut storage)
+	.unwrap();
+	let mut ext = sp_io::TestExternalities::new(storage);
+	ext.execute_with(|| System::set_block_number(1));
+	ext
+}
+
+/// Advance the mock round index by one, mirroring what `start_session` does in a real runtime.
+pub fn roll_round() {
+	<parachain_staking::Round<Test>>::mutate(|round| *round = round.saturating_add(1));
+}
@@ -0,0 +1,123 @@
+//! Behavioral tests for the parachain-staking pallet.
+
+use crate::{
+	self as parachain_staking, conviction::Conviction, mock::*, types::CollatorCandidate, Error,
+};
+use frame_support::{assert_noop, assert_ok};
+use sp_runtime::traits::Zero;
+
+fn join_candidate(who: u64, bond: u128) {
+	assert_ok!(ParachainStaking::join_candidates(Origin::signed(who), bond));
+}
+
+#[test]
+fn conviction_boosts_delegated_stake() {
+	new_test_ext(vec![(1, 1_000), (2, 1_000)]).execute_with(|| {
+		join_candidate(1, 500);
+
+		assert_ok!(ParachainStaking::join_delegators(
+			Origin::signed(2),
+			1,
+			100,
+			Conviction::Locked2x,
+		));
+
+		let delegator = ParachainStaking::delegator_state(2).unwrap();
+		// `Conviction::Locked2x` boosts the locked capital 2x; only the capital itself is locked.
+		assert_eq!(delegator.delegations[0].follows.capital, 100);
+		assert_eq!(delegator.delegations[0].follows.stake, 200);
+		assert_eq!(delegator.total, 100);
+
+		let candidate = ParachainStaking::candidate_info(1).unwrap();
+		assert_eq!(candidate.total_counted, 500 + 200);
+	});
+}
+
+#[test]
+fn scheduled_revoke_matures_after_delay() {
+	new_test_ext(vec![(1, 1_000), (2, 1_000)]).execute_with(|| {
+		join_candidate(1, 500);
+		assert_ok!(ParachainStaking::join_delegators(Origin::signed(2), 1, 100, Conviction::None));
+
+		assert_ok!(ParachainStaking::schedule_revoke_delegation(Origin::signed(2), 1));
+		// `RevokeDelegationDelay` is 2 rounds; executing before then must fail.
+		assert_noop!(
+			ParachainStaking::execute_delegation_request(Origin::signed(2), 2, 1),
+			Error::<Test>::PendingDelegationRequestNotDueYet,
+		);
+
+		roll_round();
+		roll_round();
+		assert_ok!(ParachainStaking::execute_delegation_request(Origin::signed(2), 2, 1));
+
+		// The capital is unstaking, not yet unlockable: `Conviction::None` still serves the flat
+		// `StakeDuration` (2 rounds) before `unlock_unstaked` will release it.
+		assert_noop!(
+			ParachainStaking::unlock_unstaked(Origin::signed(2)),
+			Error::<Test>::NothingToUnlock,
+		);
+
+		roll_round();
+		roll_round();
+		assert_ok!(ParachainStaking::unlock_unstaked(Origin::signed(2)));
+		assert!(pallet_balances::Locks::<Test>::get(2).is_empty());
+	});
+}
+
+#[test]
+fn top_delegations_displace_and_refund_the_smallest() {
+	new_test_ext(vec![(1, 1_000), (2, 1_000), (3, 1_000), (4, 1_000)]).execute_with(|| {
+		join_candidate(1, 0);
+		// `MaxDelegatorsPerCandidate` is 2: the first two delegations fill the top-N outright.
+		assert_ok!(ParachainStaking::join_delegators(Origin::signed(2), 1, 100, Conviction::None));
+		assert_ok!(ParachainStaking::join_delegators(Origin::signed(3), 1, 100, Conviction::None));
+
+		// Too small to displace either existing entry (both boosted stake 10).
+		assert_noop!(
+			ParachainStaking::join_delegators(Origin::signed(4), 1, 50, Conviction::None),
+			Error::<Test>::InsufficientToDisplaceLowestDelegation,
+		);
+
+		// Large enough (boosted stake 100 at Locked1x) to displace the lowest entry. Equal stakes
+		// keep insertion order, so the lowest is delegator 3's, which joined last.
+		assert_ok!(ParachainStaking::join_delegators(
+			Origin::signed(4),
+			1,
+			100,
+			Conviction::Locked1x,
+		));
+
+		let candidate: CollatorCandidate<u64, u128, MaxDelegatorsPerCandidate> =
+			ParachainStaking::candidate_info(1).unwrap();
+		assert_eq!(candidate.top_delegations.len(), 2);
+		assert!(candidate.top_delegations.iter().all(|bond| bond.owner != 3));
+
+		// Delegator 3 was auto-scheduled for an immediate refund.
+		assert_ok!(ParachainStaking::execute_delegation_request(Origin::signed(3), 3, 1));
+	});
+}
+
+#[test]
+fn reward_per_token_is_monotonic_and_claimable_by_top_n() {
+	new_test_ext(vec![(1, 1_000), (2, 1_000), (3, 1_000)]).execute_with(|| {
+		join_candidate(1, 0);
+		// `MaxDelegatorsPerCandidate` is 2, so both delegations start out counted.
+		assert_ok!(ParachainStaking::join_delegators(Origin::signed(2), 1, 100, Conviction::None));
+		assert_ok!(ParachainStaking::join_delegators(Origin::signed(3), 1, 50, Conviction::None));
+
+		ParachainStaking::record_reward_payout(&1, 150);
+		let first = ParachainStaking::reward_pools(1).last_recorded_reward_per_token;
+		assert!(!first.is_zero());
+
+		// A pot dip must never claw back the accumulator.
+		ParachainStaking::record_reward_payout(&1, 100);
+		assert_eq!(ParachainStaking::reward_pools(1).last_recorded_reward_per_token, first);
+
+		ParachainStaking::record_reward_payout(&1, 300);
+		let second = ParachainStaking::reward_pools(1).last_recorded_reward_per_token;
+		assert!(second > first);
+
+		assert_ok!(ParachainStaking::claim_delegator_rewards(Origin::signed(2)));
+		assert_ok!(ParachainStaking::claim_delegator_rewards(Origin::signed(3)));
+	});
+}
@@ -0,0 +1,174 @@
+//! Supporting types for the parachain-staking (direct delegation) pallet.
+
+use codec::{Decode, Encode, MaxEncodedLen};
+use frame_support::{traits::Get, BoundedVec};
+use scale_info::TypeInfo;
+use sp_runtime::{FixedU128, RuntimeDebug};
+
+/// A collator's cumulative reward paid out per unit of counted stake, expressed as a high
+/// precision fixed point ratio. Only ever allowed to increase.
+pub type RewardPerToken = FixedU128;
+
+/// A round (session) index.
+pub type RoundIndex = u32;
+
+/// A simple staked bond of `amount` held by `owner`.
+#[derive(Clone, Encode, Decode, RuntimeDebug, TypeInfo, PartialEq, Eq)]
+pub struct Bond<AccountId, Balance> {
+	pub owner: AccountId,
+	pub amount: Balance,
+}
+
+impl<AccountId: Ord, Balance: PartialOrd> Bond<AccountId, Balance> {
+	pub fn from_owner(owner: AccountId, amount: Balance) -> Self {
+		Bond { owner, amount }
+	}
+}
+
+/// The weight a delegation counts for once conviction is applied, alongside the capital that is
+/// actually locked from the delegator's account.
+///
+/// `stake` is the boosted value that counts toward a collator's total stake for selection and
+/// reward apportionment; `capital` is strictly what gets locked and ultimately returned.
+#[derive(Clone, Copy, Encode, Decode, RuntimeDebug, TypeInfo, PartialEq, Eq, Default)]
+pub struct Follows<Balance> {
+	pub stake: Balance,
+	pub capital: Balance,
+}
+
+/// The activity status of a collator candidate.
+#[derive(Clone, Copy, Encode, Decode, RuntimeDebug, TypeInfo, PartialEq, Eq)]
+pub enum CollatorStatus {
+	/// Staked and eligible to be selected for the active set.
+	Active,
+	/// Requested to leave; holds the round `ExitQueueDelay` rounds after the request.
+	Leaving(RoundIndex),
+}
+
+/// Snapshot of a collator candidate's own bond and aggregate stake.
+///
+/// A candidate can accumulate an unbounded number of delegations (tracked only in aggregate, via
+/// `total_backing`), but only its top `MaxTopDelegations` by boosted weight are kept in
+/// `top_delegations` and counted in `total_counted`. Selection and reward apportionment use
+/// `total_counted`, bounding a candidate's liability regardless of how many dust delegations pile
+/// up behind the cut-off.
+#[derive(Clone, Encode, Decode, RuntimeDebug, TypeInfo, PartialEq, Eq)]
+#[scale_info(skip_type_params(MaxTopDelegations))]
+pub struct CollatorCandidate<AccountId, Balance, MaxTopDelegations: Get<u32>> {
+	/// The collator's own account.
+	pub id: AccountId,
+	/// The collator's own bonded stake.
+	pub bond: Balance,
+	/// The top `MaxTopDelegations` delegations by boosted `stake`, sorted in descending order.
+	pub top_delegations: BoundedVec<Bond<AccountId, Balance>, MaxTopDelegations>,
+	/// `bond` plus the boosted `stake` of every delegation the candidate has received, counted
+	/// or not. Useful for reporting full exposure, but never used for selection or rewards.
+	pub total_backing: Balance,
+	/// `bond` plus the boosted `stake` of only the entries in `top_delegations`. This is what
+	/// counts toward selection and reward apportionment.
+	pub total_counted: Balance,
+	/// Whether the candidate is active or winding down.
+	pub status: CollatorStatus,
+}
+
+impl<AccountId, Balance: Copy + Ord, MaxTopDelegations: Get<u32>>
+	CollatorCandidate<AccountId, Balance, MaxTopDelegations>
+{
+	pub fn new(id: AccountId, bond: Balance) -> Self {
+		CollatorCandidate {
+			id,
+			bond,
+			top_delegations: BoundedVec::default(),
+			total_backing: bond,
+			total_counted: bond,
+			status: CollatorStatus::Active,
+		}
+	}
+
+	pub fn is_active(&self) -> bool {
+		matches!(self.status, CollatorStatus::Active)
+	}
+
+	pub fn is_leaving(&self) -> bool {
+		matches!(self.status, CollatorStatus::Leaving(_))
+	}
+
+	/// The smallest entry currently in the top-N set, if any.
+	pub fn lowest_top_delegation(&self) -> Option<&Bond<AccountId, Balance>> {
+		self.top_delegations.last()
+	}
+
+	/// Insert `bond` into `top_delegations`, keeping it sorted in descending order by `amount`.
+	/// Returns the evicted entry, if the set was already full.
+	pub fn insert_top_delegation(
+		&mut self,
+		bond: Bond<AccountId, Balance>,
+	) -> Option<Bond<AccountId, Balance>> {
+		let evicted = if (self.top_delegations.len() as u32) >= MaxTopDelegations::get() {
+			self.top_delegations.pop()
+		} else {
+			None
+		};
+		let position = self.top_delegations.iter().position(|b| b.amount < bond.amount);
+		let index = position.unwrap_or(self.top_delegations.len());
+		self.top_delegations
+			.try_insert(index, bond)
+			.expect("just made room for exactly one more entry; qed");
+		evicted
+	}
+}
+
+/// A single delegation from a delegator to a collator candidate, carrying the [`Conviction`]
+/// that was used to compute its [`Follows`] weight.
+///
+/// [`Conviction`]: crate::conviction::Conviction
+#[derive(Clone, Encode, Decode, RuntimeDebug, TypeInfo, PartialEq, Eq)]
+pub struct Delegation<AccountId, Balance> {
+	/// The collator candidate this delegation backs.
+	pub owner: AccountId,
+	/// The conviction the delegator chose for this delegation.
+	pub conviction: crate::conviction::Conviction,
+	/// The boosted `stake` counted toward the collator's total, and the `capital` actually
+	/// locked from the delegator's account.
+	pub follows: Follows<Balance>,
+	/// The collator's `reward_per_token` last time this delegation's rewards were settled.
+	pub reward_per_token_checkpoint: RewardPerToken,
+	/// Rewards settled but not yet claimed.
+	pub pending_rewards: Balance,
+}
+
+/// A collator's reward-per-token accumulator and running payout totals for its delegator pool.
+///
+/// `last_recorded_reward_per_token` is only ever allowed to increase: a transient deficit (e.g.
+/// the pot dipping near the existential deposit) must never claw back an already-credited reward.
+#[derive(Clone, Encode, Decode, RuntimeDebug, TypeInfo, PartialEq, Eq, Default)]
+pub struct RewardPool<Balance> {
+	/// Cumulative reward paid out per unit of counted stake, as of `last_recorded_total_payouts`.
+	pub last_recorded_reward_per_token: RewardPerToken,
+	/// `pot_balance + total_rewards_claimed + total_commission_claimed` as of the last time the
+	/// accumulator was updated. Used to compute the newly-minted delta on the next payout.
+	pub last_recorded_total_payouts: Balance,
+	/// Total rewards delegators have claimed out of this pool so far.
+	pub total_rewards_claimed: Balance,
+	/// Total commission the collator has claimed out of this pool so far.
+	pub total_commission_claimed: Balance,
+}
+
+/// A scheduled request to revoke a delegation, recorded by `schedule_revoke_delegation` /
+/// `schedule_leave_delegators` and only applied once `when` has passed.
+#[derive(Clone, Copy, Encode, Decode, RuntimeDebug, TypeInfo, PartialEq, Eq, MaxEncodedLen)]
+pub struct DelegationRequest {
+	/// The round at which the revoke becomes executable.
+	pub when: RoundIndex,
+}
+
+/// A delegator's full set of delegations.
+#[derive(Clone, Encode, Decode, RuntimeDebug, TypeInfo, PartialEq, Eq)]
+pub struct Delegator<AccountId, Balance> {
+	/// The delegator's own account.
+	pub id: AccountId,
+	/// Every delegation currently held by this delegator.
+	pub delegations: sp_std::vec::Vec<Delegation<AccountId, Balance>>,
+	/// Sum of `capital` locked across all delegations.
+	pub total: Balance,
+}